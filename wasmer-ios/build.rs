@@ -12,4 +12,53 @@ fn main() {
 
     // Set environment variable to help wasmer's build script
     println!("cargo:rustc-env=WAMR_DISABLE_FLOAT_ABI=1");
+
+    // Expose the locked `wasmer` engine version so the compiled-module
+    // cache (src/cache.rs) can key on the actual engine in use instead of
+    // just this crate's own version, which can be bumped (or not) out of
+    // step with a `wasmer` dependency upgrade.
+    let wasmer_version = locked_wasmer_version().unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=WASMER_IOS_WASMER_VERSION={}", wasmer_version);
+}
+
+/// Scrape the `wasmer` package's locked version out of `Cargo.lock`, walking
+/// up from `CARGO_MANIFEST_DIR` to find it (it lives at the workspace root,
+/// not necessarily alongside this crate). Returns `None` if no lockfile or
+/// no matching entry is found; callers fall back to an "unknown" tag rather
+/// than failing the build over it.
+fn locked_wasmer_version() -> Option<String> {
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").ok()?);
+    let mut dir = manifest_dir.as_path();
+    loop {
+        let candidate = dir.join("Cargo.lock");
+        if candidate.is_file() {
+            // Rerun when the lockfile changes so a `wasmer` upgrade updates
+            // the cache's engine tag.
+            println!("cargo:rerun-if-changed={}", candidate.display());
+            return parse_locked_version(&candidate, "wasmer");
+        }
+        dir = dir.parent()?;
+    }
+}
+
+/// Find `version = "..."` immediately following a `name = "<name>"` line
+/// inside a `[[package]]` block of a `Cargo.lock`.
+fn parse_locked_version(lock_path: &PathBuf, name: &str) -> Option<String> {
+    let contents = fs::read_to_string(lock_path).ok()?;
+    let name_line = format!("name = \"{}\"", name);
+    let mut lines = contents.lines();
+    while let Some(line) = lines.next() {
+        if line.trim() == name_line {
+            for next in lines.by_ref() {
+                let trimmed = next.trim();
+                if let Some(rest) = trimmed.strip_prefix("version = \"") {
+                    return rest.strip_suffix('"').map(|s| s.to_string());
+                }
+                if trimmed.is_empty() || trimmed.starts_with('[') {
+                    break;
+                }
+            }
+        }
+    }
+    None
 }
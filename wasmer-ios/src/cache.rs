@@ -0,0 +1,198 @@
+//! Disk-backed cache for compiled WASM modules.
+//!
+//! Compiling (and, for WAMR, validating) a module is the dominant cost of a
+//! `wasmer_execute` call when the same WASM binary (e.g. a CPython runtime
+//! passed through `wasmer_python_execute`) is reused across invocations.
+//! This cache keys each compiled artifact by a SHA-256 of its source bytes
+//! plus a version tag built from the locked `wasmer` engine version, so
+//! bumping that dependency automatically invalidates stale entries instead
+//! of deserializing into a mismatched engine.
+//!
+//! Some backends (the WAMR interpreter this crate currently ships, in
+//! particular) don't support `Module::serialize`/`deserialize` at all, in
+//! which case there is no compiled artifact here to cache in the first
+//! place. The first failed `serialize` logs once and disables `load`/`store`
+//! for the rest of the process, so an unsupported backend pays the cost of
+//! exactly one failing `serialize` call instead of one on every execution.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, Once};
+
+use sha2::{Digest, Sha256};
+use wasmer::{Module, Store};
+
+/// Bumped whenever the compiled artifact format could change (engine
+/// upgrade, target/feature flags, etc.) so old cache entries are ignored
+/// rather than deserialized into a mismatched engine. Keys on the locked
+/// `wasmer` engine version (set by `build.rs` from `Cargo.lock`), not just
+/// this crate's own version, since a `wasmer` dependency bump can change the
+/// serialized artifact format without this crate's version changing at all.
+const CACHE_VERSION_TAG: &str = concat!(
+    "wasmer-ios-cache-v1-",
+    env!("CARGO_PKG_VERSION"),
+    "-engine-",
+    env!("WASMER_IOS_WASMER_VERSION")
+);
+
+/// Set the first time `module.serialize()` fails, so later `store`/`load`
+/// calls short-circuit instead of paying for a failing serialize (and, for
+/// `load`, a filesystem probe that can never hit) on every single
+/// compilation. WAMR's interpreter backend in particular doesn't implement
+/// `serialize`/`deserialize` at all, which would otherwise make the cache
+/// pure overhead rather than a no-op.
+static SERIALIZE_UNSUPPORTED: AtomicBool = AtomicBool::new(false);
+
+/// Set alongside [`SERIALIZE_UNSUPPORTED`] so the "this backend doesn't
+/// support caching" message is logged once rather than never (since once the
+/// flag is set, `store` returns before reaching its own logging) or on every
+/// call (before the flag is set).
+static SERIALIZE_UNSUPPORTED_LOGGED: Once = Once::new();
+
+static CACHE_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Set the directory used to persist compiled modules. The caller (the iOS
+/// host app) is expected to pass an app-appropriate `Caches` directory; the
+/// directory is created on first use if it doesn't already exist.
+pub fn set_cache_dir(dir: PathBuf) {
+    *CACHE_DIR.lock().unwrap() = Some(dir);
+}
+
+/// Remove every cached artifact. Safe to call even if no cache dir was set.
+pub fn clear() -> io::Result<()> {
+    if let Some(dir) = CACHE_DIR.lock().unwrap().clone() {
+        match fs::remove_dir_all(&dir) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e),
+        }
+    } else {
+        Ok(())
+    }
+}
+
+/// Look up a compiled module for `wasm_bytes` in the cache, deserializing it
+/// if present. Returns `None` (rather than an error) on any cache miss or
+/// failure to deserialize, since a cache problem should fall back to a
+/// normal compile rather than fail the whole execution.
+pub fn load(store: &Store, wasm_bytes: &[u8]) -> Option<Module> {
+    if SERIALIZE_UNSUPPORTED.load(Ordering::Relaxed) {
+        return None;
+    }
+
+    let path = entry_path(wasm_bytes)?;
+    let bytes = fs::read(&path).ok()?;
+
+    // Safety: `Module::deserialize` requires the bytes to have come from a
+    // matching `Module::serialize` call. We only ever read back artifacts
+    // this cache itself wrote, under a key that encodes the engine/version
+    // tag, so a stale or foreign file simply fails to deserialize instead of
+    // being treated as valid.
+    match unsafe { Module::deserialize(store, bytes) } {
+        Ok(module) => Some(module),
+        Err(e) => {
+            eprintln!("wasmer-ios: cache entry {:?} failed to deserialize, recompiling: {}", path, e);
+            let _ = fs::remove_file(&path);
+            None
+        }
+    }
+}
+
+/// Persist a freshly compiled module so future calls with the same WASM
+/// bytes can skip recompilation. Failures are logged and otherwise ignored;
+/// a cache write failure must not fail the execution that produced `module`.
+pub fn store(wasm_bytes: &[u8], module: &Module) {
+    if SERIALIZE_UNSUPPORTED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let Some(path) = entry_path(wasm_bytes) else {
+        return;
+    };
+    let Some(dir) = path.parent() else { return };
+
+    if let Err(e) = fs::create_dir_all(dir) {
+        eprintln!("wasmer-ios: failed to create cache dir {:?}: {}", dir, e);
+        return;
+    }
+
+    let serialized = match module.serialize() {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            SERIALIZE_UNSUPPORTED.store(true, Ordering::Relaxed);
+            SERIALIZE_UNSUPPORTED_LOGGED.call_once(|| {
+                eprintln!(
+                    "wasmer-ios: module serialization is unsupported on this backend ({}); \
+                     disabling the compiled-module cache for the rest of this process",
+                    e
+                );
+            });
+            return;
+        }
+    };
+
+    // Write to a temp file in the same directory and atomically rename into
+    // place, so a process killed mid-write can never leave a corrupt
+    // artifact behind for a later `load` to trip over.
+    let tmp_path = path.with_extension("tmp");
+    if let Err(e) = fs::write(&tmp_path, &serialized) {
+        eprintln!("wasmer-ios: failed to write cache temp file {:?}: {}", tmp_path, e);
+        return;
+    }
+    if let Err(e) = fs::rename(&tmp_path, &path) {
+        eprintln!("wasmer-ios: failed to finalize cache file {:?}: {}", path, e);
+        let _ = fs::remove_file(&tmp_path);
+    }
+}
+
+fn entry_path(wasm_bytes: &[u8]) -> Option<PathBuf> {
+    let dir = CACHE_DIR.lock().unwrap().clone()?;
+    Some(dir.join(format!("{}.bin", cache_key(wasm_bytes))))
+}
+
+fn cache_key(wasm_bytes: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(CACHE_VERSION_TAG.as_bytes());
+    hasher.update(wasm_bytes);
+    hex_encode(&hasher.finalize())
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    use std::fmt::Write;
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        let _ = write!(out, "{:02x}", byte);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_encode_is_lowercase_and_zero_padded() {
+        assert_eq!(hex_encode(&[0x00, 0x0f, 0xff, 0xab]), "000fffab");
+        assert_eq!(hex_encode(&[]), "");
+    }
+
+    #[test]
+    fn cache_key_is_stable_for_the_same_bytes() {
+        let wasm_bytes = b"not a real wasm module, just some bytes";
+        assert_eq!(cache_key(wasm_bytes), cache_key(wasm_bytes));
+    }
+
+    #[test]
+    fn cache_key_differs_for_different_bytes() {
+        assert_ne!(cache_key(b"module a"), cache_key(b"module b"));
+    }
+
+    #[test]
+    fn cache_key_is_a_64_char_hex_sha256() {
+        let key = cache_key(b"module");
+        assert_eq!(key.len(), 64);
+        assert!(key.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+}
@@ -1,5 +1,10 @@
+mod cache;
+mod reactor;
+
+use std::error::Error as _;
 use std::ffi::CStr;
 use std::os::raw::c_char;
+use std::path::PathBuf;
 use std::slice;
 use std::sync::Arc;
 use std::os::unix::io::{RawFd, FromRawFd};
@@ -7,17 +12,63 @@ use std::io::SeekFrom;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use wasmer::{Store, Module, Instance, Value};
-use wasmer_wasix::{WasiEnvBuilder, PluggableRuntime};
+use wasmer_wasix::{WasiEnvBuilder, PluggableRuntime, PreopenDirBuilder, WasiError};
 use wasmer_wasix::runtime::task_manager::tokio::TokioTaskManager;
 use wasmer_wasix::virtual_fs::{VirtualFile, FsError};
-use tokio::io::{AsyncRead, AsyncWrite, AsyncSeek, ReadBuf};
+use tokio::io::unix::AsyncFd;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncSeek, Interest, ReadBuf};
+
+/// Stable, sysexits-inspired exit codes for failures that never reach a
+/// guest-reported `proc_exit`. iOS callers can switch on these instead of
+/// treating every non-WASI-exit failure as the same opaque error. An invalid
+/// WASM binary or a failed instantiation is not mapped to one of these: the
+/// FFI entry points report those as -1.
+mod sysexits {
+    /// An unexpected trap (not a WASI `proc_exit`) while running `_start` or
+    /// `_initialize`.
+    pub const EX_SOFTWARE: i32 = 70;
+    /// Configuration problem: an invalid directory mapping or environment
+    /// entry passed through the FFI, or a module with no `_start`, `main` or
+    /// `_initialize` export.
+    pub const EX_CONFIG: i32 = 78;
+    /// Permission denied (e.g. a requested preopened directory is not
+    /// accessible to the host process).
+    pub const EX_NOPERM: i32 = 77;
+}
+
+/// A raw fd used purely for reactor registration via [`AsyncFd`]: it owns
+/// and closes its own fd, but is never read from or written to directly
+/// (actual I/O goes through `FdFile::file` on a separate descriptor). It is
+/// left in blocking mode: a dup shares its file status flags with the
+/// original descriptor, so setting `O_NONBLOCK` on it would also change the
+/// blocking read/write behavior callers see from `FdFile` itself.
+#[derive(Debug)]
+struct RawFdHandle(RawFd);
+
+impl std::os::unix::io::AsRawFd for RawFdHandle {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
+    }
+}
+
+impl Drop for RawFdHandle {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.0);
+        }
+    }
+}
 
 // Custom VirtualFile implementation that wraps a file descriptor
 #[derive(Debug)]
 struct FdFile {
-    #[allow(dead_code)]
     fd: RawFd,
     file: tokio::fs::File,
+    /// `None` for fds the reactor can't register readiness interest for
+    /// (regular files in particular, which the kernel always reports
+    /// ready); those fall back to the always-ready behavior below instead
+    /// of ever reporting `Pending`.
+    async_fd: Option<AsyncFd<RawFdHandle>>,
 }
 
 impl FdFile {
@@ -33,41 +84,148 @@ impl FdFile {
         let std_file = unsafe { std::fs::File::from_raw_fd(dup_fd) };
         let file = tokio::fs::File::from_std(std_file);
 
-        Ok(Self { fd: dup_fd, file })
+        let async_fd = Self::register_async_fd(dup_fd);
+
+        Ok(Self { fd: dup_fd, file, async_fd })
+    }
+
+    /// Register a second dup of `fd` with tokio's reactor for real,
+    /// waker-driven readiness notifications. Returns `None` if
+    /// registration isn't possible (e.g. a regular file, which mio refuses
+    /// to register and which the kernel reports ready unconditionally
+    /// anyway).
+    fn register_async_fd(fd: RawFd) -> Option<AsyncFd<RawFdHandle>> {
+        let poll_fd = unsafe { libc::dup(fd) };
+        if poll_fd < 0 {
+            return None;
+        }
+
+        // No I/O goes through this fd, so it stays in blocking mode: it
+        // shares its file status flags with `fd`, and `O_NONBLOCK` here would
+        // make reads/writes through `FdFile::file` (and the caller's fd)
+        // non-blocking too.
+        AsyncFd::with_interest(RawFdHandle(poll_fd), Interest::READABLE | Interest::WRITABLE).ok()
     }
+
+    /// `fstat(2)` the underlying fd. Returns `None` (rather than panicking)
+    /// on failure, e.g. if the fd is a closed pipe end — callers fall back
+    /// to reporting zero (or, for `set_len`, denying) in that case.
+    fn stat(&self) -> Option<libc::stat> {
+        unsafe {
+            let mut st: libc::stat = std::mem::zeroed();
+            if libc::fstat(self.fd, &mut st) == 0 {
+                Some(st)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// Convert a `(seconds, nanoseconds)` pair from `stat(2)` into the single
+/// nanosecond-since-epoch `u64` WASIX timestamps expect.
+fn timespec_to_nanos(secs: i64, nsecs: i64) -> u64 {
+    (secs.max(0) as u64)
+        .saturating_mul(1_000_000_000)
+        .saturating_add(nsecs.max(0) as u64)
+}
+
+fn is_regular_file(mode: libc::mode_t) -> bool {
+    (mode & libc::S_IFMT) == libc::S_IFREG
+}
+
+/// Extract true file-creation time from a `stat(2)` result. On Apple
+/// targets (this crate's actual deployment target) `st_ctime` is inode
+/// *change* time, not creation time — `st_birthtime` is the field that
+/// means what `VirtualFile::created_time` wants. Non-Apple `libc::stat`
+/// doesn't expose a birth time at all, so other targets (e.g. Linux, used
+/// for `cargo test` in CI) fall back to change time as the closest
+/// approximation.
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "tvos", target_os = "watchos"))]
+fn birth_time_nanos(st: &libc::stat) -> u64 {
+    timespec_to_nanos(st.st_birthtime, st.st_birthtime_nsec)
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "ios", target_os = "tvos", target_os = "watchos")))]
+fn birth_time_nanos(st: &libc::stat) -> u64 {
+    timespec_to_nanos(st.st_ctime, st.st_ctime_nsec)
 }
 
 impl VirtualFile for FdFile {
     fn last_accessed(&self) -> u64 {
-        0 // Not implemented for FDs
+        self.stat()
+            .map(|st| timespec_to_nanos(st.st_atime, st.st_atime_nsec))
+            .unwrap_or(0)
     }
 
     fn last_modified(&self) -> u64 {
-        0 // Not implemented for FDs
+        self.stat()
+            .map(|st| timespec_to_nanos(st.st_mtime, st.st_mtime_nsec))
+            .unwrap_or(0)
     }
 
     fn created_time(&self) -> u64 {
-        0 // Not implemented for FDs
+        self.stat().map(|st| birth_time_nanos(&st)).unwrap_or(0)
     }
 
     fn size(&self) -> u64 {
-        0 // Unknown size for FDs
+        self.stat().map(|st| st.st_size as u64).unwrap_or(0)
     }
 
-    fn set_len(&mut self, _new_size: u64) -> Result<(), FsError> {
-        Err(FsError::PermissionDenied)
+    fn set_len(&mut self, new_size: u64) -> Result<(), FsError> {
+        let is_regular = self.stat().map(|st| is_regular_file(st.st_mode)).unwrap_or(false);
+        if !is_regular {
+            // Truncating a pipe/socket/tty doesn't make sense; keep denying it.
+            return Err(FsError::PermissionDenied);
+        }
+
+        let ret = unsafe { libc::ftruncate(self.fd, new_size as libc::off_t) };
+        if ret == 0 {
+            Ok(())
+        } else {
+            Err(FsError::IOError)
+        }
     }
 
     fn unlink(&mut self) -> Result<(), FsError> {
         Ok(()) // No-op for FDs
     }
 
-    fn poll_read_ready(self: std::pin::Pin<&mut Self>, _cx: &mut std::task::Context<'_>) -> std::task::Poll<std::io::Result<usize>> {
-        std::task::Poll::Ready(Ok(1))
+    fn poll_read_ready(self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> std::task::Poll<std::io::Result<usize>> {
+        match &self.async_fd {
+            Some(async_fd) => match async_fd.poll_read_ready(cx) {
+                std::task::Poll::Ready(Ok(mut guard)) => {
+                    // We don't read through `async_fd` itself (the actual
+                    // read happens on `self.file`'s own descriptor via
+                    // `AsyncRead` below), so clear the guard's readiness
+                    // immediately rather than leaving it latched — otherwise
+                    // the next call here would report ready without
+                    // rechecking the fd at all.
+                    guard.clear_ready();
+                    std::task::Poll::Ready(Ok(1))
+                }
+                std::task::Poll::Ready(Err(e)) => std::task::Poll::Ready(Err(e)),
+                std::task::Poll::Pending => std::task::Poll::Pending,
+            },
+            // Couldn't register with the reactor (regular file): the kernel
+            // always reports these ready, so match that instead of blocking
+            // forever waiting for a notification that will never come.
+            None => std::task::Poll::Ready(Ok(1)),
+        }
     }
 
-    fn poll_write_ready(self: std::pin::Pin<&mut Self>, _cx: &mut std::task::Context<'_>) -> std::task::Poll<std::io::Result<usize>> {
-        std::task::Poll::Ready(Ok(1))
+    fn poll_write_ready(self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> std::task::Poll<std::io::Result<usize>> {
+        match &self.async_fd {
+            Some(async_fd) => match async_fd.poll_write_ready(cx) {
+                std::task::Poll::Ready(Ok(mut guard)) => {
+                    guard.clear_ready();
+                    std::task::Poll::Ready(Ok(1))
+                }
+                std::task::Poll::Ready(Err(e)) => std::task::Poll::Ready(Err(e)),
+                std::task::Poll::Pending => std::task::Poll::Pending,
+            },
+            None => std::task::Poll::Ready(Ok(1)),
+        }
     }
 }
 
@@ -144,20 +302,240 @@ pub extern "C" fn wasmer_execute(
     };
 
     // Convert arguments from C strings to Rust strings
-    let mut args: Vec<String> = Vec::new();
+    let args = unsafe { parse_args(args_ptr, args_len) };
+
+    // Execute the WASM module. This predates `wasmer_execute_ex` and keeps
+    // its original behavior of forwarding the host environment, so existing
+    // callers (including `wasmer_python_execute`, which relies on it to see
+    // `PYTHONHOME`/`PYTHONPATH`) aren't broken out from under them; use
+    // `wasmer_execute_ex` for an isolated-by-default environment instead.
+    match execute_wasm(wasm_bytes, &args, stdin_fd, stdout_fd, stderr_fd, &[], &[], true) {
+        Ok(exit_code) => exit_code,
+        Err(e) => {
+            eprintln!("wasmer-ios error: {}", e);
+            -1
+        }
+    }
+}
+
+/// A single host-directory-to-guest-path mapping, passed across the FFI
+/// boundary as a fixed-layout struct rather than parallel arrays since each
+/// entry now carries three independent fields.
+#[repr(C)]
+pub struct WasmerDirMapping {
+    /// Path to the directory on the host filesystem to expose.
+    pub host_path: *const c_char,
+    /// Path the guest will see this directory mounted at.
+    pub guest_path: *const c_char,
+    /// Non-zero to deny writes from the guest into this directory.
+    pub read_only: i32,
+}
+
+/// Owned, validated form of [`WasmerDirMapping`] used once we're past the
+/// FFI boundary.
+struct DirMapping {
+    host_path: String,
+    guest_path: String,
+    read_only: bool,
+}
+
+/// Execute a WebAssembly module, additionally preopening a set of sandboxed
+/// host directories so the guest can access files the host app explicitly
+/// chose to share (e.g. a Documents subfolder) instead of only stdio.
+///
+/// # Parameters
+/// Same as [`wasmer_execute`], plus:
+/// - `dirs_ptr`: Pointer to an array of [`WasmerDirMapping`] entries
+/// - `dirs_len`: Number of entries in `dirs_ptr`
+#[no_mangle]
+pub extern "C" fn wasmer_execute_with_dirs(
+    wasm_bytes_ptr: *const u8,
+    wasm_bytes_len: usize,
+    args_ptr: *const *const c_char,
+    args_len: usize,
+    stdin_fd: i32,
+    stdout_fd: i32,
+    stderr_fd: i32,
+    dirs_ptr: *const WasmerDirMapping,
+    dirs_len: usize,
+) -> i32 {
+    if wasm_bytes_ptr.is_null() || args_ptr.is_null() {
+        eprintln!("wasmer-ios: null pointer provided");
+        return -1;
+    }
+    if dirs_len > 0 && dirs_ptr.is_null() {
+        eprintln!("wasmer-ios: null dirs pointer provided with non-zero dirs_len");
+        return -1;
+    }
+
+    let wasm_bytes = unsafe { slice::from_raw_parts(wasm_bytes_ptr, wasm_bytes_len) };
+    let args = unsafe { parse_args(args_ptr, args_len) };
+    let dirs = match unsafe { parse_dirs(dirs_ptr, dirs_len) } {
+        Ok(dirs) => dirs,
+        Err(msg) => {
+            eprintln!("wasmer-ios: {}", msg);
+            return sysexits::EX_CONFIG;
+        }
+    };
+
+    // Predates environment isolation too; keep forwarding the host
+    // environment for the same reason as `wasmer_execute` above.
+    match execute_wasm(wasm_bytes, &args, stdin_fd, stdout_fd, stderr_fd, &dirs, &[], true) {
+        Ok(exit_code) => exit_code,
+        Err(e) => {
+            eprintln!("wasmer-ios error: {}", e);
+            -1
+        }
+    }
+}
+
+unsafe fn ptr_to_str(ptr: *const c_char) -> Option<String> {
+    if ptr.is_null() {
+        return None;
+    }
+    CStr::from_ptr(ptr).to_str().ok().map(|s| s.to_string())
+}
+
+/// Convert a C array of argument strings into owned `String`s, the same way
+/// every `wasmer_execute*`/`wasmer_reactor_instantiate` entry point needs to.
+/// A null or non-UTF-8 entry is silently skipped rather than failing the
+/// whole call, matching the original inline behavior.
+unsafe fn parse_args(args_ptr: *const *const c_char, args_len: usize) -> Vec<String> {
+    let mut args = Vec::new();
     for i in 0..args_len {
-        unsafe {
-            let arg_ptr = *args_ptr.add(i);
-            if !arg_ptr.is_null() {
-                if let Ok(arg_str) = CStr::from_ptr(arg_ptr).to_str() {
-                    args.push(arg_str.to_string());
-                }
+        let arg_ptr = *args_ptr.add(i);
+        if !arg_ptr.is_null() {
+            if let Ok(arg_str) = CStr::from_ptr(arg_ptr).to_str() {
+                args.push(arg_str.to_string());
             }
         }
     }
+    args
+}
+
+/// Convert a C array of [`WasmerDirMapping`] entries into owned
+/// [`DirMapping`]s. Returns `Err` with a ready-to-log message identifying the
+/// offending index if a path is missing/non-UTF-8, so callers can surface
+/// `sysexits::EX_CONFIG` the same way they did before this was extracted.
+unsafe fn parse_dirs(
+    dirs_ptr: *const WasmerDirMapping,
+    dirs_len: usize,
+) -> Result<Vec<DirMapping>, String> {
+    let mut dirs = Vec::new();
+    for i in 0..dirs_len {
+        let entry = &*dirs_ptr.add(i);
+        let host_path = ptr_to_str(entry.host_path)
+            .ok_or_else(|| format!("invalid host_path in dir mapping {}", i))?;
+        let guest_path = ptr_to_str(entry.guest_path)
+            .ok_or_else(|| format!("invalid guest_path in dir mapping {}", i))?;
+        dirs.push(DirMapping {
+            host_path,
+            guest_path,
+            read_only: entry.read_only != 0,
+        });
+    }
+    Ok(dirs)
+}
+
+/// Convert a C array of `"KEY=VALUE"` strings into owned `(key, value)`
+/// pairs, as used by `wasmer_execute_ex`'s `env_ptr`/`env_len`. Returns
+/// `Err` with a ready-to-log message identifying the offending index on a
+/// null entry, non-UTF-8 entry, or one missing the `=` separator.
+unsafe fn parse_env(
+    env_ptr: *const *const c_char,
+    env_len: usize,
+) -> Result<Vec<(String, String)>, String> {
+    let mut env = Vec::new();
+    for i in 0..env_len {
+        let entry_ptr = *env_ptr.add(i);
+        if entry_ptr.is_null() {
+            return Err(format!("null entry in env array at index {}", i));
+        }
+        let entry = CStr::from_ptr(entry_ptr)
+            .to_str()
+            .map_err(|_| format!("env entry {} is not valid UTF-8", i))?;
+        let pair = parse_env_entry(entry)
+            .ok_or_else(|| format!("env entry {} is not in KEY=VALUE form", i))?;
+        env.push(pair);
+    }
+    Ok(env)
+}
+
+/// Split a single `"KEY=VALUE"` environment entry into its owned parts.
+/// `None` if there's no `=` separator at all.
+fn parse_env_entry(entry: &str) -> Option<(String, String)> {
+    let (key, value) = entry.split_once('=')?;
+    Some((key.to_string(), value.to_string()))
+}
+
+/// Execute a WebAssembly module with explicit control over both the guest's
+/// preopened directories and its environment, instead of the host process
+/// environment being forwarded implicitly.
+///
+/// # Parameters
+/// Same as [`wasmer_execute_with_dirs`], plus:
+/// - `env_ptr`: Pointer to an array of `"KEY=VALUE"` C strings
+/// - `env_len`: Number of entries in `env_ptr`
+/// - `inherit_host_env`: Non-zero to additionally inherit the host process's
+///   environment (`env_ptr` entries still take precedence). Defaults to an
+///   isolated, explicit-only environment when zero, so a sandboxed guest
+///   never sees host/app secrets unless the caller opts in.
+#[no_mangle]
+pub extern "C" fn wasmer_execute_ex(
+    wasm_bytes_ptr: *const u8,
+    wasm_bytes_len: usize,
+    args_ptr: *const *const c_char,
+    args_len: usize,
+    stdin_fd: i32,
+    stdout_fd: i32,
+    stderr_fd: i32,
+    dirs_ptr: *const WasmerDirMapping,
+    dirs_len: usize,
+    env_ptr: *const *const c_char,
+    env_len: usize,
+    inherit_host_env: i32,
+) -> i32 {
+    if wasm_bytes_ptr.is_null() || args_ptr.is_null() {
+        eprintln!("wasmer-ios: null pointer provided");
+        return -1;
+    }
+    if dirs_len > 0 && dirs_ptr.is_null() {
+        eprintln!("wasmer-ios: null dirs pointer provided with non-zero dirs_len");
+        return -1;
+    }
+    if env_len > 0 && env_ptr.is_null() {
+        eprintln!("wasmer-ios: null env pointer provided with non-zero env_len");
+        return -1;
+    }
 
-    // Execute the WASM module
-    match execute_wasm(wasm_bytes, &args, stdin_fd, stdout_fd, stderr_fd) {
+    let wasm_bytes = unsafe { slice::from_raw_parts(wasm_bytes_ptr, wasm_bytes_len) };
+    let args = unsafe { parse_args(args_ptr, args_len) };
+    let dirs = match unsafe { parse_dirs(dirs_ptr, dirs_len) } {
+        Ok(dirs) => dirs,
+        Err(msg) => {
+            eprintln!("wasmer-ios: {}", msg);
+            return sysexits::EX_CONFIG;
+        }
+    };
+
+    let env = match unsafe { parse_env(env_ptr, env_len) } {
+        Ok(env) => env,
+        Err(msg) => {
+            eprintln!("wasmer-ios: {}", msg);
+            return sysexits::EX_CONFIG;
+        }
+    };
+
+    match execute_wasm(
+        wasm_bytes,
+        &args,
+        stdin_fd,
+        stdout_fd,
+        stderr_fd,
+        &dirs,
+        &env,
+        inherit_host_env != 0,
+    ) {
         Ok(exit_code) => exit_code,
         Err(e) => {
             eprintln!("wasmer-ios error: {}", e);
@@ -166,6 +544,92 @@ pub extern "C" fn wasmer_execute(
     }
 }
 
+/// Instantiate a reactor-style WASM module (one exporting `_initialize`)
+/// and keep it alive for repeated calls via `wasmer_reactor_call`.
+///
+/// # Returns
+/// A positive opaque handle on success, or -1 on failure.
+#[no_mangle]
+pub extern "C" fn wasmer_reactor_instantiate(
+    wasm_bytes_ptr: *const u8,
+    wasm_bytes_len: usize,
+    args_ptr: *const *const c_char,
+    args_len: usize,
+    stdin_fd: i32,
+    stdout_fd: i32,
+    stderr_fd: i32,
+) -> i64 {
+    if wasm_bytes_ptr.is_null() || args_ptr.is_null() {
+        eprintln!("wasmer-ios: null pointer provided");
+        return -1;
+    }
+
+    let wasm_bytes = unsafe { slice::from_raw_parts(wasm_bytes_ptr, wasm_bytes_len) };
+    let args = unsafe { parse_args(args_ptr, args_len) };
+
+    // Predates environment isolation too; keep forwarding the host
+    // environment for the same reason as `wasmer_execute` above.
+    match reactor_instantiate(wasm_bytes, &args, stdin_fd, stdout_fd, stderr_fd, &[], &[], true) {
+        Ok(handle) => handle as i64,
+        Err(e) => {
+            eprintln!("wasmer-ios error: {}", e);
+            -1
+        }
+    }
+}
+
+/// Call an exported function on a reactor instance previously returned by
+/// `wasmer_reactor_instantiate`, passing up to `argc` `i32` arguments.
+///
+/// # Returns
+/// The function's first `i32`/`i64` result (0 if it returns nothing), or -1
+/// on failure (unknown handle, missing export, or a trap).
+#[no_mangle]
+pub extern "C" fn wasmer_reactor_call(
+    handle: i64,
+    func_name_ptr: *const c_char,
+    argv_ptr: *const i32,
+    argc: usize,
+) -> i64 {
+    if handle < 0 || func_name_ptr.is_null() {
+        eprintln!("wasmer-ios: invalid arguments to wasmer_reactor_call");
+        return -1;
+    }
+
+    let func_name = match unsafe { CStr::from_ptr(func_name_ptr) }.to_str() {
+        Ok(name) => name,
+        Err(_) => {
+            eprintln!("wasmer-ios: func_name is not valid UTF-8");
+            return -1;
+        }
+    };
+
+    let argv: Vec<i32> = if argc == 0 || argv_ptr.is_null() {
+        Vec::new()
+    } else {
+        unsafe { slice::from_raw_parts(argv_ptr, argc) }.to_vec()
+    };
+
+    match reactor::call(handle as u64, func_name, &argv) {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("wasmer-ios error: {}", e);
+            -1
+        }
+    }
+}
+
+/// Free a reactor instance previously returned by
+/// `wasmer_reactor_instantiate`. A no-op if the handle is invalid or was
+/// already freed.
+#[no_mangle]
+pub extern "C" fn wasmer_reactor_free(handle: i64) {
+    if handle < 0 {
+        return;
+    }
+    reactor::free(handle as u64);
+}
+
 /// Convenience entrypoint to execute a CPython WASM runtime using Wasmer.
 /// This simply forwards to `wasmer_execute` and exists to provide a stable
 /// symbol tailored for Python integrations on iOS.
@@ -190,33 +654,131 @@ pub extern "C" fn wasmer_python_execute(
     )
 }
 
+/// Desired worker thread count for [`global_runtime`], set via
+/// `wasmer_set_thread_pool_size` before the runtime is first used. 0 means
+/// "let tokio pick its default (the number of CPUs)".
+static THREAD_POOL_SIZE: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+/// Process-global, lazily-initialized multi-thread tokio runtime shared by
+/// every `wasmer_execute`/reactor call, instead of building a fresh
+/// current-thread runtime per call. WASIX's `thread_spawn` needs real worker
+/// threads to hand work to; a `new_current_thread` runtime can't provide
+/// that, and rebuilding a runtime on every invocation also pays its setup
+/// cost repeatedly.
+static GLOBAL_RUNTIME: std::sync::OnceLock<tokio::runtime::Runtime> = std::sync::OnceLock::new();
+
+pub(crate) fn global_runtime() -> &'static tokio::runtime::Runtime {
+    GLOBAL_RUNTIME.get_or_init(|| {
+        let mut builder = tokio::runtime::Builder::new_multi_thread();
+        builder.enable_all().thread_stack_size(8 * 1024 * 1024); // 8MB guest stack, applied to every worker thread
+        let pool_size = THREAD_POOL_SIZE.load(std::sync::atomic::Ordering::Relaxed);
+        if pool_size > 0 {
+            builder.worker_threads(pool_size);
+        }
+        builder
+            .build()
+            .expect("wasmer-ios: failed to build global tokio runtime")
+    })
+}
+
 fn execute_wasm(
     wasm_bytes: &[u8],
     args: &[String],
     stdin_fd: i32,
     stdout_fd: i32,
     stderr_fd: i32,
+    dirs: &[DirMapping],
+    env: &[(String, String)],
+    inherit_host_env: bool,
 ) -> Result<i32, Box<dyn std::error::Error>> {
-    // Create a tokio runtime for wasmer-wasix with larger stack size
-    // Default stack size may be too small for some WASM programs
-    let rt = tokio::runtime::Builder::new_current_thread()
-        .enable_all()
-        .thread_stack_size(8 * 1024 * 1024) // 8MB stack (increased from default ~2MB)
-        .build()?;
-
-    // Run the WASM execution in the tokio runtime
-    rt.block_on(async {
-        execute_wasm_async(wasm_bytes, args, stdin_fd, stdout_fd, stderr_fd).await
+    global_runtime().block_on(async {
+        execute_wasm_async(
+            wasm_bytes,
+            args,
+            stdin_fd,
+            stdout_fd,
+            stderr_fd,
+            dirs,
+            env,
+            inherit_host_env,
+        )
+        .await
     })
 }
 
-async fn execute_wasm_async(
+/// Instantiate a reactor-style module (one that exports `_initialize`
+/// rather than `_start`) and register it for repeated calls.
+fn reactor_instantiate(
     wasm_bytes: &[u8],
     args: &[String],
     stdin_fd: i32,
     stdout_fd: i32,
     stderr_fd: i32,
-) -> Result<i32, Box<dyn std::error::Error>> {
+    dirs: &[DirMapping],
+    env: &[(String, String)],
+    inherit_host_env: bool,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let rt = global_runtime();
+
+    let setup = rt.block_on(async {
+        setup_wasi_instance(
+            wasm_bytes,
+            args,
+            stdin_fd,
+            stdout_fd,
+            stderr_fd,
+            dirs,
+            env,
+            inherit_host_env,
+        )
+        .await
+    })?;
+
+    let (mut store, instance, wasi_env) = match setup {
+        WasiSetup::Ready(store, instance, wasi_env) => (store, instance, wasi_env),
+        WasiSetup::PermissionDenied(msg) => return Err(msg.into()),
+    };
+
+    let init_func = instance
+        .exports
+        .get_function("_initialize")
+        .map_err(|_| "module does not export _initialize; not a reactor".to_string())?;
+
+    {
+        // Enter the runtime for the one-time setup call too, for the same
+        // reason `reactor::call` re-enters it on every later invocation.
+        let _guard = rt.enter();
+        init_func.call(&mut store, &[] as &[Value])?;
+    }
+
+    Ok(reactor::register(store, instance, wasi_env))
+}
+
+/// Outcome of [`setup_wasi_instance`]: either a ready-to-run instance, or a
+/// recoverable permission failure (a requested directory preopen was
+/// denied) that callers should surface as a distinct error rather than a
+/// generic instantiation failure.
+enum WasiSetup {
+    Ready(Store, Instance, wasmer_wasix::WasiFunctionEnv),
+    PermissionDenied(String),
+}
+
+/// Shared setup used by both one-shot command execution and reactor
+/// instantiation: validate the binary, load (or cache) the compiled module,
+/// build the WASI environment, preopen any requested directories, and
+/// instantiate. Callers differ only in what they do with the resulting
+/// `Instance` afterwards (run `_start`/`main` once vs. call `_initialize`
+/// and keep everything alive behind a handle).
+async fn setup_wasi_instance(
+    wasm_bytes: &[u8],
+    args: &[String],
+    stdin_fd: i32,
+    stdout_fd: i32,
+    stderr_fd: i32,
+    dirs: &[DirMapping],
+    env: &[(String, String)],
+    inherit_host_env: bool,
+) -> Result<WasiSetup, Box<dyn std::error::Error>> {
     // Validate WASM binary first
     if wasm_bytes.len() < 8 {
         return Err("Invalid WASM binary: too small".into());
@@ -237,11 +799,26 @@ async fn execute_wasm_async(
     // This provides both validation and execution using the WAMR interpreter
     let mut store = Store::default();
 
-    // Load the WASM module using WAMR interpreter
-    let module = Module::new(&store, wasm_bytes)?;
+    // Load the WASM module using WAMR interpreter, reusing a cached compiled
+    // artifact when one exists for these exact bytes.
+    let module = match cache::load(&store, wasm_bytes) {
+        Some(module) => module,
+        None => {
+            let module = Module::new(&store, wasm_bytes)?;
+            cache::store(wasm_bytes, &module);
+            module
+        }
+    };
 
-    // Get environment variables
-    let env_vars: Vec<(String, String)> = std::env::vars().collect();
+    // The guest only ever sees the environment the caller explicitly passed
+    // in; the host process environment is included only if `inherit_host_env`
+    // was set, so untrusted modules can't accidentally read host/app secrets.
+    let mut env_vars: Vec<(String, String)> = env.to_vec();
+    if inherit_host_env {
+        // Skip host variables the caller set explicitly, so the explicit
+        // value is the only one the guest sees.
+        env_vars.extend(std::env::vars().filter(|(key, _)| !env.iter().any(|(k, _)| k == key)));
+    }
 
     // Build WASI environment with WASIX p1 support
     // Create a PluggableRuntime with tokio task manager
@@ -281,6 +858,34 @@ async fn execute_wasm_async(
         }
     }
 
+    // Preopen any sandboxed host directories the caller asked to share, so
+    // the guest gets a filesystem limited to exactly these paths rather than
+    // the rest of the device.
+    for dir in dirs {
+        let preopen = PreopenDirBuilder::new()
+            .directory(&dir.host_path)
+            .alias(&dir.guest_path)
+            .read(true)
+            .write(!dir.read_only)
+            .build();
+        let preopen = match preopen {
+            Ok(preopen) => preopen,
+            Err(e) => {
+                let msg = format!("failed to preopen {}: {}", dir.host_path, e);
+                eprintln!("wasmer-ios: {}", msg);
+                return Ok(WasiSetup::PermissionDenied(msg));
+            }
+        };
+        match wasi_env_builder.add_preopen(preopen) {
+            Ok(builder) => wasi_env_builder = builder,
+            Err(e) => {
+                let msg = format!("failed to map {} -> {}: {}", dir.host_path, dir.guest_path, e);
+                eprintln!("wasmer-ios: {}", msg);
+                return Ok(WasiSetup::PermissionDenied(msg));
+            }
+        }
+    }
+
     let mut wasi_env = wasi_env_builder.finalize(&mut store)?;
 
     // Generate WASI imports
@@ -293,6 +898,35 @@ async fn execute_wasm_async(
     // This is critical - it sets up wasi_env.inner
     wasi_env.initialize(&mut store, instance.clone())?;
 
+    Ok(WasiSetup::Ready(store, instance, wasi_env))
+}
+
+async fn execute_wasm_async(
+    wasm_bytes: &[u8],
+    args: &[String],
+    stdin_fd: i32,
+    stdout_fd: i32,
+    stderr_fd: i32,
+    dirs: &[DirMapping],
+    env: &[(String, String)],
+    inherit_host_env: bool,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    let (mut store, instance, _wasi_env) = match setup_wasi_instance(
+        wasm_bytes,
+        args,
+        stdin_fd,
+        stdout_fd,
+        stderr_fd,
+        dirs,
+        env,
+        inherit_host_env,
+    )
+    .await?
+    {
+        WasiSetup::Ready(store, instance, wasi_env) => (store, instance, wasi_env),
+        WasiSetup::PermissionDenied(_) => return Ok(sysexits::EX_NOPERM),
+    };
+
     // Find and call the _start or main function
     let exit_code = if let Ok(start_func) = instance.exports.get_function("_start") {
         // WASI command pattern
@@ -302,7 +936,7 @@ async fn execute_wasm_async(
                 0
             }
             Err(e) => {
-                // Check if this is a WASI exit
+                // Check if this is a WASI exit raised via proc_exit
                 if let Some(exit_code) = extract_exit_code(&e) {
                     exit_code
                 } else {
@@ -319,12 +953,13 @@ async fn execute_wasm_async(
                             eprintln!("    {:?}", frame);
                         }
                     }
-                    1
+                    sysexits::EX_SOFTWARE
                 }
             }
         }
     } else if let Ok(main_func) = instance.exports.get_function("main") {
-        // Reactor pattern
+        // Plain `main` export, distinct from the `_initialize`-based reactor
+        // pattern handled below and in the dedicated wasmer_reactor_* FFI.
         match main_func.call(&mut store, &[] as &[Value]) {
             Ok(results) => {
                 // Extract exit code from return value
@@ -350,30 +985,61 @@ async fn execute_wasm_async(
                 1
             }
         }
+    } else if let Ok(init_func) = instance.exports.get_function("_initialize") {
+        // Reactor-shaped module called through the one-shot `wasmer_execute`
+        // path: run its one-time setup and return, since nothing here keeps
+        // the instance alive to call further exports afterwards. Callers
+        // that need to invoke exports repeatedly should use
+        // `wasmer_reactor_instantiate`/`wasmer_reactor_call` instead.
+        match init_func.call(&mut store, &[] as &[Value]) {
+            Ok(_) => 0,
+            Err(e) => {
+                eprintln!("wasmer-ios: Error calling _initialize");
+                eprintln!("  Error: {}", e);
+                sysexits::EX_SOFTWARE
+            }
+        }
     } else {
-        eprintln!("wasmer-ios: No _start or main function found in WASM module");
+        eprintln!("wasmer-ios: No _start, main, or _initialize function found in WASM module");
         eprintln!("  Available exports:");
         for (name, _) in instance.exports.iter() {
             eprintln!("    - {}", name);
         }
-        -1
+        sysexits::EX_CONFIG
     };
 
     Ok(exit_code)
 }
 
+/// Recover the real exit code from a trap raised by `proc_exit`.
+///
+/// WASIX surfaces `proc_exit` as a `WasiError::Exit` wrapped inside the
+/// `RuntimeError`'s downcast/source chain rather than as a plain string, so
+/// we have to walk that chain structurally instead of pattern-matching on
+/// the error's `Display` output.
 fn extract_exit_code(error: &wasmer::RuntimeError) -> Option<i32> {
-    // Try to extract WASI exit code from error
-    // WASI programs exit by calling proc_exit, which causes a trap
-    let error_msg = error.to_string();
-    if error_msg.contains("exit") {
-        // Try to parse exit code from error message
-        // This is a simplified approach; in production you'd want more robust parsing
-        return Some(0);
+    if let Ok(wasi_err) = error.clone().downcast::<WasiError>() {
+        return wasi_exit_to_code(&wasi_err);
     }
+
+    let mut source = error.source();
+    while let Some(err) = source {
+        if let Some(wasi_err) = err.downcast_ref::<WasiError>() {
+            return wasi_exit_to_code(wasi_err);
+        }
+        source = err.source();
+    }
+
     None
 }
 
+fn wasi_exit_to_code(error: &WasiError) -> Option<i32> {
+    match error {
+        WasiError::Exit(code) => Some((*code).raw()),
+        _ => None,
+    }
+}
+
 /// Get version information about the Wasmer runtime
 #[no_mangle]
 pub extern "C" fn wasmer_version() -> *const c_char {
@@ -381,6 +1047,52 @@ pub extern "C" fn wasmer_version() -> *const c_char {
     VERSION.as_ptr() as *const c_char
 }
 
+/// Point the compiled-module cache at a directory. The host app should pass
+/// an iOS-appropriate `Caches` directory; call this once before executing
+/// any WASM modules to enable caching (it is a no-op otherwise).
+#[no_mangle]
+pub extern "C" fn wasmer_set_cache_dir(dir_ptr: *const c_char) {
+    if dir_ptr.is_null() {
+        eprintln!("wasmer-ios: null pointer provided to wasmer_set_cache_dir");
+        return;
+    }
+
+    let dir = match unsafe { CStr::from_ptr(dir_ptr) }.to_str() {
+        Ok(dir) => dir,
+        Err(_) => {
+            eprintln!("wasmer-ios: cache dir path is not valid UTF-8");
+            return;
+        }
+    };
+
+    cache::set_cache_dir(PathBuf::from(dir));
+}
+
+/// Evict every entry from the compiled-module cache.
+#[no_mangle]
+pub extern "C" fn wasmer_clear_cache() -> i32 {
+    match cache::clear() {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("wasmer-ios: failed to clear cache: {}", e);
+            -1
+        }
+    }
+}
+
+/// Cap the worker thread count of the shared tokio runtime used for WASIX
+/// execution. Must be called before the first `wasmer_execute`/reactor call
+/// (whichever happens first initializes the runtime); later calls are
+/// logged and ignored.
+#[no_mangle]
+pub extern "C" fn wasmer_set_thread_pool_size(n: usize) {
+    if GLOBAL_RUNTIME.get().is_some() {
+        eprintln!("wasmer-ios: thread pool already initialized; wasmer_set_thread_pool_size must be called before the first execution");
+        return;
+    }
+    THREAD_POOL_SIZE.store(n, std::sync::atomic::Ordering::Relaxed);
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -390,4 +1102,119 @@ mod tests {
         let version = wasmer_version();
         assert!(!version.is_null());
     }
+
+    #[test]
+    fn timespec_to_nanos_combines_seconds_and_nanos() {
+        assert_eq!(timespec_to_nanos(1, 500), 1_000_000_500);
+        assert_eq!(timespec_to_nanos(0, 0), 0);
+    }
+
+    #[test]
+    fn timespec_to_nanos_saturates_instead_of_overflowing() {
+        assert_eq!(timespec_to_nanos(i64::MAX, i64::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn timespec_to_nanos_clamps_negative_components_to_zero() {
+        // `stat(2)` timestamps shouldn't be negative in practice, but a
+        // pre-epoch time or bad value from a wonky fd shouldn't panic or
+        // wrap around; treat it as the epoch instead.
+        assert_eq!(timespec_to_nanos(-1, -1), 0);
+        assert_eq!(timespec_to_nanos(5, -1), 5_000_000_000);
+    }
+
+    #[test]
+    fn extract_exit_code_returns_none_without_a_wasi_error_in_the_chain() {
+        // Regression test for the `downcast::<WasiError>()` call returning
+        // `Result`, not `Option`: an error with no `WasiError` anywhere in
+        // its source chain must fall through to `None` rather than
+        // panicking or misreporting an exit code.
+        let error = wasmer::RuntimeError::new("some unrelated trap, not a proc_exit");
+        assert_eq!(extract_exit_code(&error), None);
+    }
+
+    #[test]
+    fn parse_env_entry_splits_on_first_equals() {
+        assert_eq!(
+            parse_env_entry("KEY=VALUE"),
+            Some(("KEY".to_string(), "VALUE".to_string()))
+        );
+        // A value containing `=` must stay intact rather than being cut at
+        // the first one.
+        assert_eq!(
+            parse_env_entry("KEY=a=b=c"),
+            Some(("KEY".to_string(), "a=b=c".to_string()))
+        );
+        // An empty value is valid (`KEY=`), just not an empty whole entry.
+        assert_eq!(
+            parse_env_entry("KEY="),
+            Some(("KEY".to_string(), String::new()))
+        );
+    }
+
+    #[test]
+    fn parse_env_entry_rejects_missing_separator() {
+        assert_eq!(parse_env_entry("NOEQUALSSIGN"), None);
+        assert_eq!(parse_env_entry(""), None);
+    }
+
+    /// Build a NUL-terminated `CString` array and exercise `parse_dirs`
+    /// through the same raw-pointer shape the FFI boundary uses.
+    fn with_dir_mappings<R>(
+        mappings: &[(&str, &str, i32)],
+        f: impl FnOnce(*const WasmerDirMapping, usize) -> R,
+    ) -> R {
+        let cstrings: Vec<(std::ffi::CString, std::ffi::CString)> = mappings
+            .iter()
+            .map(|(host, guest, _)| {
+                (
+                    std::ffi::CString::new(*host).unwrap(),
+                    std::ffi::CString::new(*guest).unwrap(),
+                )
+            })
+            .collect();
+        let entries: Vec<WasmerDirMapping> = cstrings
+            .iter()
+            .zip(mappings)
+            .map(|((host, guest), (_, _, read_only))| WasmerDirMapping {
+                host_path: host.as_ptr(),
+                guest_path: guest.as_ptr(),
+                read_only: *read_only,
+            })
+            .collect();
+        f(entries.as_ptr(), entries.len())
+    }
+
+    #[test]
+    fn parse_dirs_converts_valid_mappings() {
+        with_dir_mappings(
+            &[("/host/a", "/guest/a", 0), ("/host/b", "/guest/b", 1)],
+            |ptr, len| {
+                let dirs = unsafe { parse_dirs(ptr, len) }.unwrap();
+                assert_eq!(dirs.len(), 2);
+                assert_eq!(dirs[0].host_path, "/host/a");
+                assert_eq!(dirs[0].guest_path, "/guest/a");
+                assert!(!dirs[0].read_only);
+                assert!(dirs[1].read_only);
+            },
+        );
+    }
+
+    #[test]
+    fn parse_dirs_rejects_null_host_path() {
+        let guest_path = std::ffi::CString::new("/guest").unwrap();
+        let entry = WasmerDirMapping {
+            host_path: std::ptr::null(),
+            guest_path: guest_path.as_ptr(),
+            read_only: 0,
+        };
+        let err = unsafe { parse_dirs(&entry, 1) }.unwrap_err();
+        assert!(err.contains("host_path"));
+    }
+
+    #[test]
+    fn parse_dirs_empty_is_ok() {
+        let dirs = unsafe { parse_dirs(std::ptr::null(), 0) }.unwrap();
+        assert!(dirs.is_empty());
+    }
 }
@@ -0,0 +1,106 @@
+//! Registry of live reactor-style WASM instances.
+//!
+//! A reactor module (one that exports `_initialize` rather than `_start`)
+//! is instantiated once and then has its exports invoked repeatedly by the
+//! host, instead of being torn down after a single run. Each live instance
+//! is kept here behind an opaque integer handle. Calls re-enter the shared
+//! [`crate::global_runtime`] rather than carrying their own runtime, since
+//! that runtime is process-global and already outlives every instance.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use wasmer::{Instance, Store, Value};
+use wasmer_wasix::WasiFunctionEnv;
+
+struct ReactorInstance {
+    store: Store,
+    instance: Instance,
+    // Kept alive for the lifetime of the instance; WASIX relies on it
+    // having been initialized even though we don't call into it directly.
+    #[allow(dead_code)]
+    wasi_env: WasiFunctionEnv,
+}
+
+static NEXT_HANDLE: AtomicU64 = AtomicU64::new(1);
+static REGISTRY: Mutex<Option<HashMap<u64, ReactorInstance>>> = Mutex::new(None);
+
+/// Register a freshly instantiated reactor module and return the handle the
+/// host will use to call into it.
+pub fn register(store: Store, instance: Instance, wasi_env: WasiFunctionEnv) -> u64 {
+    let handle = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed);
+    let mut registry = REGISTRY.lock().unwrap();
+    registry.get_or_insert_with(HashMap::new).insert(
+        handle,
+        ReactorInstance {
+            store,
+            instance,
+            wasi_env,
+        },
+    );
+    handle
+}
+
+/// Call an exported function on a previously registered reactor instance,
+/// passing `argv` as `i32` arguments. Returns the first `i32`/`i64` result,
+/// or 0 if the function has no return value.
+pub fn call(handle: u64, func_name: &str, argv: &[i32]) -> Result<i64, String> {
+    // Take the instance out of the registry for the duration of the call
+    // instead of holding the registry lock across guest execution: a long
+    // call on one handle would otherwise serialize every other handle's
+    // calls, and guest code that re-enters any `wasmer_reactor_*` FFI (e.g.
+    // via an import that calls back into the host) would deadlock on its
+    // own handle.
+    let mut reactor = {
+        let mut registry = REGISTRY.lock().unwrap();
+        let map = registry
+            .as_mut()
+            .ok_or_else(|| format!("no reactor instances registered (handle {})", handle))?;
+        map.remove(&handle)
+            .ok_or_else(|| format!("unknown reactor handle {}", handle))?
+    };
+
+    let result = (|| {
+        let func = reactor
+            .instance
+            .exports
+            .get_function(func_name)
+            .map_err(|e| format!("export {} not found: {}", func_name, e))?;
+        let args: Vec<Value> = argv.iter().map(|v| Value::I32(*v)).collect();
+
+        // Re-enter the shared runtime so anything the call spawns (WASIX
+        // background tasks, thread_spawn, etc.) still has a live task manager.
+        let _guard = crate::global_runtime().enter();
+        let results = func
+            .call(&mut reactor.store, &args)
+            .map_err(|e| format!("call to {} failed: {}", func_name, e))?;
+
+        Ok(match results.first() {
+            Some(Value::I32(v)) => *v as i64,
+            Some(Value::I64(v)) => *v,
+            _ => 0,
+        })
+    })();
+
+    // Put the instance back regardless of outcome so the handle stays valid
+    // for the next call.
+    REGISTRY
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashMap::new)
+        .insert(handle, reactor);
+
+    result
+}
+
+/// Tear down a reactor instance, dropping its store, instance, and WASI
+/// environment.
+/// Returns `false` if the handle was already freed or never existed.
+pub fn free(handle: u64) -> bool {
+    let mut registry = REGISTRY.lock().unwrap();
+    registry
+        .as_mut()
+        .map(|map| map.remove(&handle).is_some())
+        .unwrap_or(false)
+}